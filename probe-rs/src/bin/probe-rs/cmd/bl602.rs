@@ -0,0 +1,190 @@
+//! Support for the BL602-style bootable-image format: a boot header
+//! describing the firmware entry point, a partition table (optionally driven
+//! by a user-supplied TOML config), and the firmware itself relocated to its
+//! flash offsets. This is the layout `blflash` and the BL602/BL702 ROM
+//! bootloader expect, as opposed to a raw `bin`/`elf` image.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use object::{Object, ObjectSegment};
+use serde::Deserialize;
+
+/// Chip variants supported by the `bl602` format. Each maps its XIP flash
+/// window to a base address (the same one, `0x2300_0000`, for both), which is
+/// what ELF segment addresses are relative to once they land in flash.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum Bl602Chip {
+    Bl602,
+    Bl702,
+}
+
+impl Bl602Chip {
+    pub(crate) fn flash_base(self) -> u32 {
+        match self {
+            Bl602Chip::Bl602 | Bl602Chip::Bl702 => 0x2300_0000,
+        }
+    }
+}
+
+/// Options specific to the `bl602` format. Flattened directly onto `Cmd`
+/// rather than folded into `FormatOptions`: the partition table and chip
+/// variant only make sense for this one bootable-image format, not the set
+/// of flags every format shares (e.g. `bin`'s `--base-address`/`--skip`).
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct Bl602Options {
+    /// Chip variant, selects the XIP flash base address.
+    #[clap(long, value_enum, default_value = "bl602")]
+    pub(crate) chip: Bl602Chip,
+
+    /// TOML partition table to embed, in the format `blflash` accepts. If
+    /// omitted, a single partition spanning the whole image is used.
+    #[clap(long)]
+    pub(crate) partition_config: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+struct PartitionConfig {
+    #[serde(default)]
+    partition: Vec<PartitionEntry>,
+}
+
+#[derive(Deserialize)]
+struct PartitionEntry {
+    name: String,
+    address: u32,
+    size: u32,
+}
+
+const MAX_PARTITIONS: usize = 16;
+const PARTITION_MAGIC: u32 = 0x4650_4254;
+const BOOT_HEADER_MAGIC: u32 = 0x504e_4642;
+
+/// Reflected CRC-32 (poly `0xEDB88320`, init/final XOR `0xFFFFFFFF`), the same
+/// construction the ROM bootloader and `blflash` use to validate the boot
+/// header and partition table before trusting them.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+/// Serializes the partition table: magic, entry count, up to
+/// `MAX_PARTITIONS` `(name, address, size)` entries padded with zeroed slots,
+/// and a trailing CRC32 over everything before it.
+fn serialize_partition_table(entries: &[PartitionEntry]) -> Result<Vec<u8>> {
+    if entries.len() > MAX_PARTITIONS {
+        anyhow::bail!("Partition config has {} entries, but only {MAX_PARTITIONS} are supported.", entries.len());
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&PARTITION_MAGIC.to_le_bytes());
+    body.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+    for entry in entries {
+        let mut name = [0u8; 12];
+        let name_bytes = entry.name.as_bytes();
+        let len = name_bytes.len().min(name.len());
+        name[..len].copy_from_slice(&name_bytes[..len]);
+
+        body.extend_from_slice(&name);
+        body.extend_from_slice(&entry.address.to_le_bytes());
+        body.extend_from_slice(&entry.size.to_le_bytes());
+    }
+    for _ in entries.len()..MAX_PARTITIONS {
+        body.extend_from_slice(&[0u8; 20]);
+    }
+
+    let crc = crc32(&body);
+    body.extend_from_slice(&crc.to_le_bytes());
+    Ok(body)
+}
+
+/// Boot header the ROM bootloader reads first: a magic, the firmware entry
+/// point, the image's total length, and a CRC32 over the rest of the header.
+fn serialize_boot_header(entry_point: u32, image_length: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(16);
+    header.extend_from_slice(&BOOT_HEADER_MAGIC.to_le_bytes());
+    header.extend_from_slice(&entry_point.to_le_bytes());
+    header.extend_from_slice(&image_length.to_le_bytes());
+    header.extend_from_slice(&crc32(&header).to_le_bytes());
+    header
+}
+
+fn read_partitions(options: &Bl602Options, header_len: u32, image_size: u32) -> Result<Vec<PartitionEntry>> {
+    match &options.partition_config {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read partition config {}", path.display()))?;
+            let config: PartitionConfig =
+                toml::from_str(&contents).context("Failed to parse partition config as TOML.")?;
+            Ok(config.partition)
+        }
+        // The caller places segments starting right after the header and
+        // partition table, so the default single-partition layout needs to
+        // start there too, not at flash offset 0.
+        None => Ok(vec![PartitionEntry { name: "app".into(), address: header_len, size: image_size }]),
+    }
+}
+
+/// Size of a serialized boot header: magic, entry point, image length, CRC32.
+const BOOT_HEADER_LEN: u32 = 16;
+
+/// Size of a serialized partition table: magic, entry count, `MAX_PARTITIONS`
+/// fixed-size `(name, address, size)` slots, and a trailing CRC32.
+const PARTITION_TABLE_LEN: u32 = 4 + 4 + MAX_PARTITIONS as u32 * 20 + 4;
+
+/// Parses `elf`'s segments, relocates each from its load address to a flash
+/// offset (by subtracting `options.chip`'s XIP flash base), and lays out
+/// `[boot header][partition table][segments, each at its own flash offset]`
+/// exactly as the ROM bootloader expects. Returns the image to write starting
+/// at the chip's flash base address.
+///
+/// The firmware is linked as though it starts right at the flash base (flash
+/// offset 0), but the header and partition table need that space too, so
+/// everything the firmware cares about -- the entry point, and the default
+/// single-partition layout's address -- is shifted forward by the combined
+/// header length to land where the segments actually end up.
+pub(crate) fn build_image(elf: &[u8], options: &Bl602Options) -> Result<Vec<u8>> {
+    let object = object::File::parse(elf).context("Failed to parse ELF file for bl602 format.")?;
+    let flash_base = options.chip.flash_base();
+    let header_len = BOOT_HEADER_LEN + PARTITION_TABLE_LEN;
+
+    let mut segments = Vec::new();
+    for segment in object.segments() {
+        let data = segment.data().context("Failed to read ELF segment data.")?;
+        if data.is_empty() {
+            continue;
+        }
+
+        let load_address = segment.address() as u32;
+        let flash_offset = load_address.checked_sub(flash_base).with_context(|| {
+            format!("Segment at {load_address:#010x} is below the chip's flash base {flash_base:#010x}.")
+        })?;
+
+        segments.push((flash_offset, data));
+    }
+
+    let image_length: u32 = segments.iter().map(|(_, data)| data.len() as u32).sum();
+    let partition_table = serialize_partition_table(&read_partitions(options, header_len, image_length)?)?;
+    let boot_header = serialize_boot_header(object.entry() as u32 + header_len, image_length);
+
+    let mut image = boot_header;
+    image.extend_from_slice(&partition_table);
+    assert_eq!(image.len() as u32, header_len, "boot header + partition table size drifted from the reserved layout");
+
+    for (offset, data) in segments {
+        let start = (header_len + offset) as usize;
+        if image.len() < start + data.len() {
+            image.resize(start + data.len(), 0xFF);
+        }
+        image[start..start + data.len()].copy_from_slice(data);
+    }
+
+    Ok(image)
+}
@@ -1,13 +1,14 @@
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, Cursor, Read, Write};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use probe_rs::debug::DebugInfo;
-use probe_rs::flashing::{FileDownloadError, Format};
+use probe_rs::flashing::{BinOptions, FileDownloadError, Format};
 use probe_rs::{Core, VectorCatchCondition};
 use probe_rs_target::MemoryRegion;
 use signal_hook::consts::signal;
@@ -18,6 +19,9 @@ use crate::util::flash::run_flash_download;
 use crate::util::rtt::{self, RttConfig};
 use crate::FormatOptions;
 
+mod bl602;
+use bl602::Bl602Options;
+
 #[derive(clap::Parser)]
 pub struct Cmd {
     #[clap(flatten)]
@@ -37,8 +41,16 @@ pub struct Cmd {
     #[clap(long)]
     pub(crate) chip_erase: bool,
 
+    /// Drop into an interactive debug console whenever the core halts, instead
+    /// of just printing a stacktrace.
+    #[clap(long)]
+    pub(crate) interactive: bool,
+
     #[clap(flatten)]
     pub(crate) format_options: FormatOptions,
+
+    #[clap(flatten)]
+    pub(crate) bl602_options: Bl602Options,
 }
 
 impl Cmd {
@@ -62,6 +74,18 @@ impl Cmd {
                 Format::Elf => loader.load_elf_data(&mut file),
                 Format::Hex => loader.load_hex_data(&mut file),
                 Format::Idf(options) => loader.load_idf_data(&mut session, &mut file, options),
+                Format::Bl602(_options) => {
+                    let mut elf = Vec::new();
+                    file.read_to_end(&mut elf).map_err(FileDownloadError::IO)?;
+
+                    let image = bl602::build_image(&elf, &self.bl602_options)
+                        .map_err(|e| FileDownloadError::IO(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())))?;
+
+                    loader.load_bin_data(
+                        &mut Cursor::new(image),
+                        BinOptions { base_address: Some(self.bl602_options.chip.flash_base()), skip: 0 },
+                    )
+                }
             }?;
 
             run_flash_download(
@@ -89,6 +113,7 @@ impl Cmd {
             path,
             timestamp_offset,
             self.always_print_stacktrace,
+            self.interactive,
         )?;
 
         Ok(())
@@ -96,13 +121,15 @@ impl Cmd {
 }
 
 /// Print all RTT messsages and a stacktrace when the core stops due to an exception
-/// or when ctrl + c is pressed.
+/// or when ctrl + c is pressed. If `interactive` is set, a halt instead drops
+/// into a debug console, with RTT polling kept alive while at the prompt.
 fn run_loop(
     core: &mut Core<'_>,
     memory_map: &[MemoryRegion],
     path: &Path,
     timestamp_offset: UtcOffset,
     always_print_stacktrace: bool,
+    interactive: bool,
 ) -> Result<bool, anyhow::Error> {
     let rtt_config = rtt::RttConfig::default();
     let mut rtta = attach_to_rtt(core, memory_map, path, rtt_config, timestamp_offset);
@@ -110,10 +137,12 @@ fn run_loop(
     let exit = Arc::new(AtomicBool::new(false));
     let sig_id = signal_hook::flag::register(signal::SIGINT, exit.clone())?;
 
+    let mut repl = interactive.then(DebugRepl::new);
+
     let mut stdout = std::io::stdout();
     while !exit.load(Ordering::Relaxed) {
         let had_rtt_data = poll_rtt(&mut rtta, core, &mut stdout)?;
-        if poll_stacktrace(core, path)? {
+        if poll_halt(core, path, &mut repl)? {
             return Ok(false);
         }
 
@@ -155,6 +184,36 @@ fn poll_stacktrace(core: &mut Core<'_>, path: &Path) -> Result<bool> {
     })
 }
 
+/// Checks whether the core is halted and, if so, either prints a stacktrace and
+/// reports the run as finished (the non-interactive behavior), or hands control
+/// to `repl` so the user can inspect and resume the target. Returns `true` if
+/// `run_loop` should exit.
+fn poll_halt(core: &mut Core<'_>, path: &Path, repl: &mut Option<DebugRepl>) -> Result<bool> {
+    let Some(repl) = repl else {
+        return poll_stacktrace(core, path);
+    };
+
+    if !matches!(core.status()?, probe_rs::CoreStatus::Halted(_)) {
+        return Ok(false);
+    }
+
+    if !repl.printed_stacktrace {
+        let registers = core.registers();
+        let pc_register = registers.pc().expect("a program counter register");
+        print_stacktrace(core, pc_register, path)?;
+        repl.printed_stacktrace = true;
+    }
+
+    match repl.poll(core, path)? {
+        Some(ReplAction::Continue) => {
+            core.run()?;
+            repl.printed_stacktrace = false;
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
+
 /// Prints the stacktrace of the current execution state.
 fn print_stacktrace(
     core: &mut Core<'_>,
@@ -227,6 +286,176 @@ fn poll_rtt(
     Ok(had_data)
 }
 
+/// What a debug console command asked the run loop to do next.
+enum ReplAction {
+    /// Resume the core and go back to polling RTT / waiting for the next halt.
+    Continue,
+}
+
+/// Interactive, monitor-style debug console used by `run_loop` when
+/// `--interactive` is passed. Reads commands from a background thread so RTT
+/// can keep being polled while a prompt is up, supporting:
+///
+/// - `bp <addr>` / `del <addr>`: set / clear a hardware breakpoint
+/// - `c`: continue
+/// - `s`: single-step
+/// - `reg`: dump core registers
+/// - `mem <addr> <len>`: read target memory
+/// - `bt`: print a stacktrace
+/// - an empty line repeats the last command
+struct DebugRepl {
+    breakpoints: Vec<u64>,
+    last_command: Option<String>,
+    input: Receiver<String>,
+    /// Whether the `probe-rs>` prompt has already been printed since the last
+    /// command, so we don't reprint it on every RTT-polling iteration while
+    /// waiting for input.
+    prompted: bool,
+    /// Whether the stacktrace for the current halt has already been printed,
+    /// so running a command (`reg`, `mem`, `s`, ...) doesn't reprint it on
+    /// every subsequent poll of the same halt. Reset when the core resumes.
+    printed_stacktrace: bool,
+}
+
+impl DebugRepl {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let stdin = std::io::stdin();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match stdin.lock().read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if tx.send(line.trim().to_string()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Self {
+            breakpoints: Vec::new(),
+            last_command: None,
+            input: rx,
+            prompted: false,
+            printed_stacktrace: false,
+        }
+    }
+
+    /// Prints the prompt (once per command) and processes at most one completed
+    /// command line, without blocking if none is available yet.
+    fn poll(&mut self, core: &mut Core<'_>, path: &Path) -> Result<Option<ReplAction>> {
+        if !self.prompted {
+            print!("probe-rs> ");
+            std::io::stdout().flush()?;
+            self.prompted = true;
+        }
+
+        let line = match self.input.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => return Ok(None),
+            // The stdin thread gave up (e.g. stdin closed); just keep running.
+            Err(TryRecvError::Disconnected) => return Ok(Some(ReplAction::Continue)),
+        };
+
+        let command = if line.is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = Some(line.clone());
+            Some(line)
+        };
+
+        self.prompted = false;
+
+        match command {
+            Some(command) => self.run_command(core, path, &command),
+            None => Ok(None),
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        core: &mut Core<'_>,
+        path: &Path,
+        command: &str,
+    ) -> Result<Option<ReplAction>> {
+        let mut args = command.split_whitespace();
+        match args.next().unwrap_or_default() {
+            "c" | "continue" => return Ok(Some(ReplAction::Continue)),
+            "s" | "step" => {
+                core.step()?;
+                print_registers(core)?;
+            }
+            "bp" => match args.next().and_then(parse_address) {
+                Some(address) => {
+                    core.set_hw_breakpoint(address)?;
+                    self.breakpoints.push(address);
+                    println!("Breakpoint set at {address:#010x}");
+                }
+                None => println!("usage: bp <addr>"),
+            },
+            "del" => match args.next().and_then(parse_address) {
+                Some(address) => {
+                    core.clear_hw_breakpoint(address)?;
+                    self.breakpoints.retain(|bp| *bp != address);
+                    println!("Breakpoint cleared at {address:#010x}");
+                }
+                None => println!("usage: del <addr>"),
+            },
+            "reg" => print_registers(core)?,
+            "mem" => match (args.next().and_then(parse_address), args.next().and_then(|l| l.parse().ok())) {
+                (Some(address), Some(len)) => print_memory(core, address, len)?,
+                _ => println!("usage: mem <addr> <len>"),
+            },
+            "bt" => {
+                let registers = core.registers();
+                let pc_register = registers.pc().expect("a program counter register");
+                print_stacktrace(core, pc_register, path)?;
+            }
+            "" => {}
+            other => println!("unknown command: {other}"),
+        }
+
+        Ok(None)
+    }
+}
+
+/// Parses a `0x`-prefixed hex address or a plain decimal one.
+fn parse_address(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Dumps the core's registers, one per line.
+fn print_registers(core: &mut Core<'_>) -> Result<()> {
+    let registers = core.registers();
+    for register in registers.core_registers() {
+        let value: u64 = core.read_core_reg(register)?;
+        println!("{:>6} = {:#010x}", register.name(), value);
+    }
+    Ok(())
+}
+
+/// Reads and hex-dumps `len` bytes of target memory starting at `address`.
+fn print_memory(core: &mut Core<'_>, address: u64, len: usize) -> Result<()> {
+    let mut buffer = vec![0u8; len];
+    core.read(address, &mut buffer)?;
+
+    for (i, chunk) in buffer.chunks(16).enumerate() {
+        let offset = address + (i * 16) as u64;
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+        println!("{offset:#010x}: {}", hex.join(" "));
+    }
+
+    Ok(())
+}
+
 /// Attach to the RTT buffers.
 fn attach_to_rtt(
     core: &mut Core<'_>,
@@ -0,0 +1,85 @@
+//! Ties the flash algorithm primitives in [`super::flasher`] together into the
+//! pipeline a full image download needs: erase, pipelined programming, then
+//! verification (via the on-chip CRC32 analyzer when available, falling back
+//! to a plain read-back comparison otherwise).
+
+use super::flasher::{sector_bytes, ActiveFlasher, Erase, FlasherError, InactiveFlasher, Operation, Program, SectorInfo, Verify};
+use super::FlashRegion;
+
+/// Erases, programs, and verifies `image` (which starts at `image_base`)
+/// against `flash_region`. Returns the base address of every sector that
+/// failed verification; an empty `Vec` means the download verified clean.
+pub fn download_image(
+    inactive: &mut InactiveFlasher,
+    flash_region: FlashRegion,
+    image_base: u32,
+    image: &[u8],
+) -> Result<Vec<u32>, FlasherError> {
+    let mut erase = inactive.init::<Erase>(flash_region, None, None)?;
+    erase.erase_all()?;
+
+    let mut program = erase.uninit()?.init::<Program>(flash_region, None, None)?;
+
+    let page_size = program.get_page_info(image_base).map_or(image.len() as u32, |page| page.size);
+    let pages: Vec<(u32, &[u8])> = image
+        .chunks(page_size as usize)
+        .enumerate()
+        .map(|(i, chunk)| (image_base + i as u32 * page_size, chunk))
+        .collect();
+    program.program_pages(&pages)?;
+
+    let sectors = sectors_covering(&program, image_base, image.len() as u32);
+    let analyzer_supported = program.get_flash_info(image_base).is_some_and(|info| info.crc_supported);
+
+    let mismatched = if analyzer_supported {
+        let mut verify = program.uninit()?.init::<Verify>(flash_region, None, None)?;
+        let mismatched = verify.verify(image_base, image, &sectors)?;
+        *inactive = verify.uninit()?;
+        mismatched
+    } else {
+        let mismatched = read_back_verify(&mut program, image_base, image, &sectors);
+        *inactive = program.uninit()?;
+        mismatched
+    };
+
+    Ok(mismatched)
+}
+
+/// Walks `get_sector_info` from `image_base` up to `image_base + image_len`,
+/// collecting the sectors the image covers.
+fn sectors_covering(program: &ActiveFlasher<'_, Program>, image_base: u32, image_len: u32) -> Vec<SectorInfo> {
+    let mut sectors = Vec::new();
+    let mut address = image_base;
+
+    while address < image_base + image_len {
+        let Some(sector) = program.get_sector_info(address) else { break };
+        address = sector.base_address + sector.size;
+        sectors.push(sector);
+    }
+
+    sectors
+}
+
+/// Verification fallback for algorithms without CRC32 analyzer support:
+/// reads each sector back over the debug link and compares it directly to
+/// the expected image bytes (treating bytes past the end of `image` as
+/// erased `0xFF`, same as the analyzer path).
+fn read_back_verify<O: Operation>(
+    flasher: &mut ActiveFlasher<'_, O>,
+    image_base: u32,
+    image: &[u8],
+    sectors: &[SectorInfo],
+) -> Vec<u32> {
+    let mut mismatched = Vec::new();
+
+    for sector in sectors {
+        let mut actual = vec![0u8; sector.size as usize];
+        flasher.read_bytes(sector.base_address, &mut actual);
+
+        if actual != sector_bytes(image_base, image, sector) {
+            mismatched.push(sector.base_address);
+        }
+    }
+
+    mismatched
+}
@@ -1,8 +1,84 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::time::{Duration, Instant};
+
 use ::memory::MI;
 use crate::session::Session;
 
 use super::*;
 
+/// Default time to wait for a flash algorithm call to complete before giving up
+/// with `FlasherError::Timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Yields back to the executor once, so an async flasher future shares the
+/// thread with other work (RTT polling, a second core's flasher future, ...)
+/// instead of monopolizing it with a busy loop.
+async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    YieldNow(false).await
+}
+
+/// Wakes the thread that is blocked in `block_on` by unparking it.
+fn thread_waker() -> Waker {
+    fn clone(ptr: *const ()) -> RawWaker {
+        let thread = unsafe { Arc::from_raw(ptr as *const std::thread::Thread) };
+        let cloned = Arc::into_raw(thread.clone());
+        std::mem::forget(thread);
+        RawWaker::new(cloned as *const (), &VTABLE)
+    }
+    fn wake(ptr: *const ()) {
+        let thread = unsafe { Arc::from_raw(ptr as *const std::thread::Thread) };
+        thread.unpark();
+    }
+    fn wake_by_ref(ptr: *const ()) {
+        let thread = unsafe { &*(ptr as *const std::thread::Thread) };
+        thread.unpark();
+    }
+    fn drop(ptr: *const ()) {
+        unsafe { Arc::from_raw(ptr as *const std::thread::Thread) };
+    }
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+    let thread = Arc::new(std::thread::current());
+    let raw = RawWaker::new(Arc::into_raw(thread) as *const (), &VTABLE);
+    unsafe { Waker::from_raw(raw) }
+}
+
+/// Blocks the current thread until `future` resolves, parking between polls
+/// instead of busy-looping. This is the blocking shim that lets the async
+/// flasher core (`wait_for_completion_async`) back the existing synchronous API.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = thread_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
 const ANALYZER: [u32; 49] = [
     0x2780b5f0, 0x25004684, 0x4e2b2401, 0x447e4a2b, 0x0023007f, 0x425b402b, 0x40130868, 0x08584043,
     0x425b4023, 0x40584013, 0x40200843, 0x40104240, 0x08434058, 0x42404020, 0x40584010, 0x40200843,
@@ -81,6 +157,39 @@ pub enum FlasherError {
     UnalignedFlashWriteAddress,
     UnalignedPhraseLength,
     ProgramPhrase(u32, u32),
+    Verify(u32),
+    AnalyzerNotSupported,
+    Timeout,
+}
+
+/// Minimum number of free RAM bytes the analyzer needs after `analyzer_address`
+/// for its own code and the 256-word CRC lookup table it builds at runtime.
+/// CRC results are written to `analyzer_address + ANALYZER_MIN_FREE_BYTES`.
+const ANALYZER_MIN_FREE_BYTES: u32 = 0x600;
+
+/// Maximum number of sectors verified in a single analyzer invocation, so the
+/// CRC result buffer stays a small, fixed size regardless of image size: at
+/// most this many words (0x400 bytes), placed right after the
+/// `ANALYZER_MIN_FREE_BYTES` the analyzer itself uses, so that much RAM past
+/// them has to be free as well.
+const ANALYZER_MAX_SECTORS_PER_CALL: usize = 256;
+
+/// Computes the standard reflected CRC-32 (poly `0xEDB88320`, init `0xFFFFFFFF`,
+/// final XOR `0xFFFFFFFF`) that the on-chip analyzer produces, so results read
+/// back from the target can be compared against the expected image contents.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc ^ 0xFFFF_FFFF
 }
 
 pub struct InactiveFlasher<'a> {
@@ -102,6 +211,7 @@ impl<'a> InactiveFlasher<'a> {
         let mut flasher = ActiveFlasher {
             session: self.session,
             region,
+            timeout: DEFAULT_TIMEOUT,
             _operation: core::marker::PhantomData,
         };
 
@@ -114,7 +224,7 @@ impl<'a> InactiveFlasher<'a> {
                 Some(O::operation()),
                 None,
                 true
-            );
+            )?;
 
             if result != 0 {
                 return Err(FlasherError::Init(result));
@@ -128,10 +238,18 @@ impl<'a> InactiveFlasher<'a> {
 pub struct ActiveFlasher<'a, O: Operation> {
     session: &'a mut Session,
     region: FlashRegion,
+    /// How long to wait for a flash algorithm call to complete before giving up
+    /// with `FlasherError::Timeout`. Defaults to `DEFAULT_TIMEOUT`.
+    timeout: Duration,
     _operation: core::marker::PhantomData<O>,
 }
 
 impl<'a, O: Operation> ActiveFlasher<'a, O> {
+    /// Overrides how long to wait for a flash algorithm call to complete.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
     pub fn uninit(&mut self) -> Result<InactiveFlasher, FlasherError> {
         let algo = self.session.target.info.flash_algorithm;
 
@@ -143,7 +261,7 @@ impl<'a, O: Operation> ActiveFlasher<'a, O> {
                 None,
                 None,
                 false
-            );
+            )?;
 
             if result != 0 {
                 return Err(FlasherError::Uninit(result));
@@ -155,9 +273,20 @@ impl<'a, O: Operation> ActiveFlasher<'a, O> {
         })
     }
 
-    fn call_function_and_wait(&mut self, pc: u32, r0: Option<u32>, r1: Option<u32>, r2: Option<u32>, r3: Option<u32>, init: bool) -> u32 {
+    /// Runs `call_function` and waits for completion, yielding between polls
+    /// instead of busy-looping, so this future can be driven alongside other
+    /// async work (e.g. polling RTT, or flashing another core). This is the
+    /// async entry point `erase_sector_async`/`program_page_async` build on;
+    /// it is exposed directly for callers that need to drive a custom flash
+    /// algorithm call without going through those helpers.
+    pub async fn call_function_and_wait_async(&mut self, pc: u32, r0: Option<u32>, r1: Option<u32>, r2: Option<u32>, r3: Option<u32>, init: bool) -> Result<u32, FlasherError> {
         self.call_function(pc, r0, r1, r2, r3, init);
-        self.wait_for_completion()
+        self.wait_for_completion_async().await
+    }
+
+    /// Blocking shim over `call_function_and_wait_async` for synchronous callers.
+    fn call_function_and_wait(&mut self, pc: u32, r0: Option<u32>, r1: Option<u32>, r2: Option<u32>, r3: Option<u32>, init: bool) -> Result<u32, FlasherError> {
+        block_on(self.call_function_and_wait_async(pc, r0, r1, r2, r3, init))
     }
 
     fn call_function(&mut self, pc: u32, r0: Option<u32>, r1: Option<u32>, r2: Option<u32>, r3: Option<u32>, init: bool) {
@@ -180,12 +309,35 @@ impl<'a, O: Operation> ActiveFlasher<'a, O> {
         self.session.target.core.run(&mut self.session.probe);
     }
 
-    fn wait_for_completion(&mut self) -> u32 {
+    /// Polls the target until it halts or `self.timeout` elapses, yielding to the
+    /// executor between polls rather than spinning, so the caller can interleave
+    /// other async work (RTT polling, a second core's flasher future, ...) on the
+    /// same task while this one waits.
+    pub async fn wait_for_completion_async(&mut self) -> Result<u32, FlasherError> {
         let regs = self.session.target.info.basic_register_addresses;
+        let deadline = Instant::now() + self.timeout;
+
+        while self.session.target.core.wait_for_core_halted(&mut self.session.probe).is_err() {
+            if Instant::now() >= deadline {
+                return Err(FlasherError::Timeout);
+            }
+            yield_now().await;
+        }
+
+        Ok(self.session.target.core.read_core_reg(&mut self.session.probe, regs.R0).unwrap())
+    }
 
-        while self.session.target.core.wait_for_core_halted(&mut self.session.probe).is_err() {}
+    /// Blocking shim over `wait_for_completion_async` for synchronous callers.
+    fn wait_for_completion(&mut self) -> Result<u32, FlasherError> {
+        block_on(self.wait_for_completion_async())
+    }
 
-        self.session.target.core.read_core_reg(&mut self.session.probe, regs.R0).unwrap()
+    /// Reads `buffer.len()` bytes of memory starting at `address` directly over
+    /// the debug link, bypassing the flash algorithm. Used for plain read-back
+    /// verification and by higher-level layers (e.g. the key/value store) that
+    /// need to inspect previously programmed flash contents.
+    pub fn read_bytes(&mut self, address: u32, buffer: &mut [u8]) {
+        self.session.probe.read_block8(address, buffer);
     }
 }
 
@@ -201,7 +353,7 @@ impl <'a> ActiveFlasher<'a, Erase> {
                 None,
                 None,
                 false
-            );
+            )?;
 
             if result != 0 {
                 Err(FlasherError::EraseAll(result))
@@ -213,17 +365,21 @@ impl <'a> ActiveFlasher<'a, Erase> {
         }
     }
 
-    pub fn erase_sector(&mut self, address: u32) -> Result<(), FlasherError> {
+    /// Async counterpart of `erase_sector`. Exposed so a caller can erase (or
+    /// program, via `program_page_async`) several cores concurrently, e.g. by
+    /// driving both futures with `futures::join!` on the same executor instead
+    /// of blocking one core's flasher on another's.
+    pub async fn erase_sector_async(&mut self, address: u32) -> Result<(), FlasherError> {
         let algo = self.session.target.info.flash_algorithm;
 
-        let result = self.call_function_and_wait(
+        let result = self.call_function_and_wait_async(
             algo.pc_erase_sector,
             Some(address),
             None,
             None,
             None,
             false
-        );
+        ).await?;
 
         if result != 0 {
             Err(FlasherError::EraseSector(result, address))
@@ -231,10 +387,18 @@ impl <'a> ActiveFlasher<'a, Erase> {
             Ok(())
         }
     }
+
+    pub fn erase_sector(&mut self, address: u32) -> Result<(), FlasherError> {
+        block_on(self.erase_sector_async(address))
+    }
 }
 
 impl <'a> ActiveFlasher<'a, Program> {
-    pub fn program_page(&mut self, address: u32, bytes: &[u8]) -> Result<(), FlasherError> {
+    /// Async counterpart of `program_page`. Exposed so a caller can program (or
+    /// erase, via `erase_sector_async`) several cores concurrently, e.g. by
+    /// driving both futures with `futures::join!` on the same executor instead
+    /// of blocking one core's flasher on another's.
+    pub async fn program_page_async(&mut self, address: u32, bytes: &[u8]) -> Result<(), FlasherError> {
         let algo = self.session.target.info.flash_algorithm;
 
         // TODO: Prevent security settings from locking the device.
@@ -242,14 +406,14 @@ impl <'a> ActiveFlasher<'a, Program> {
         // Transfer the bytes to RAM.
         self.session.probe.write_block8(algo.begin_data, bytes);
 
-        let result = self.call_function_and_wait(
+        let result = self.call_function_and_wait_async(
             algo.pc_program_page,
             Some(address),
             Some(bytes.len() as u32),
             Some(algo.begin_data),
             None,
             false
-        );
+        ).await?;
 
         if result != 0 {
             Err(FlasherError::ProgramPage(result, address))
@@ -258,11 +422,15 @@ impl <'a> ActiveFlasher<'a, Program> {
         }
     }
 
+    pub fn program_page(&mut self, address: u32, bytes: &[u8]) -> Result<(), FlasherError> {
+        block_on(self.program_page_async(address, bytes))
+    }
+
     pub fn start_program_page_with_buffer(&mut self, address: u32, buffer_number: u32) -> Result<(), FlasherError> {
         let algo = self.session.target.info.flash_algorithm;
 
         // Check the buffer number.
-        if buffer_number < algo.page_buffers.len() as u32 {
+        if buffer_number >= algo.page_buffers.len() as u32 {
             return Err(FlasherError::InvalidBufferNumber(buffer_number, algo.page_buffers.len() as u32));
         }
 
@@ -282,7 +450,7 @@ impl <'a> ActiveFlasher<'a, Program> {
         let algo = self.session.target.info.flash_algorithm;
 
         // Check the buffer number.
-        if buffer_number < algo.page_buffers.len() as u32 {
+        if buffer_number >= algo.page_buffers.len() as u32 {
             return Err(FlasherError::InvalidBufferNumber(buffer_number, algo.page_buffers.len() as u32));
         }
 
@@ -294,6 +462,49 @@ impl <'a> ActiveFlasher<'a, Program> {
         Ok(())
     }
 
+    /// Programs `pages` (each an `(address, bytes)` pair), overlapping the
+    /// host-to-RAM transfer of one page with the target programming the previous
+    /// one whenever the flash algorithm advertises at least 2 page buffers. Falls
+    /// back to sequential `program_page` calls otherwise.
+    pub fn program_pages(&mut self, pages: &[(u32, &[u8])]) -> Result<(), FlasherError> {
+        let algo = self.session.target.info.flash_algorithm;
+
+        if algo.page_buffers.len() < 2 || pages.len() < 2 {
+            for (address, bytes) in pages {
+                self.program_page(*address, bytes)?;
+            }
+            return Ok(());
+        }
+
+        // Kick off programming of the first page, then pipeline the rest: while
+        // the target programs page N into one buffer, load page N + 1 into the
+        // other buffer so the next `start_program_page_with_buffer` can start
+        // immediately once the target finishes.
+        let (first_address, first_bytes) = pages[0];
+        self.load_page_buffer(first_address, first_bytes, 0)?;
+        self.start_program_page_with_buffer(first_address, 0)?;
+
+        for (i, (address, bytes)) in pages.iter().enumerate().skip(1) {
+            let buffer_number = (i % 2) as u32;
+            self.load_page_buffer(*address, bytes, buffer_number)?;
+
+            let result = self.wait_for_completion()?;
+            if result != 0 {
+                return Err(FlasherError::ProgramPage(result, pages[i - 1].0));
+            }
+
+            self.start_program_page_with_buffer(*address, buffer_number)?;
+        }
+
+        let (last_address, _) = pages[pages.len() - 1];
+        let result = self.wait_for_completion()?;
+        if result != 0 {
+            return Err(FlasherError::ProgramPage(result, last_address));
+        }
+
+        Ok(())
+    }
+
     pub fn program_phrase(&mut self, address: u32, bytes: &[u8]) -> Result<(), FlasherError> {
         let algo = self.session.target.info.flash_algorithm;
 
@@ -324,7 +535,7 @@ impl <'a> ActiveFlasher<'a, Program> {
             Some(algo.begin_data),
             None,
             false
-        );
+        )?;
 
         if result != 0 {
             Err(FlasherError::ProgramPhrase(result, address))
@@ -370,4 +581,136 @@ impl <'a> ActiveFlasher<'a, Program> {
             crc_supported: algo.analyzer_supported,
         })
     }
+}
+
+impl <'a> ActiveFlasher<'a, Verify> {
+    /// Verifies `image` (which starts at `image_base`) against `sectors` using the
+    /// on-chip CRC32 analyzer, instead of reading the whole flash back over the
+    /// debug link. Returns the base address of every sector whose on-chip CRC does
+    /// not match the expected CRC of the corresponding image bytes. Bytes of a
+    /// sector that `image` does not cover (i.e. erased/unwritten flash) are
+    /// treated as `0xFF`.
+    ///
+    /// Returns `FlasherError::AnalyzerNotSupported` if `algo.analyzer_supported` is
+    /// false; callers should fall back to a plain read-back comparison in that case.
+    pub fn verify(&mut self, image_base: u32, image: &[u8], sectors: &[SectorInfo]) -> Result<Vec<u32>, FlasherError> {
+        let algo = self.session.target.info.flash_algorithm;
+
+        if !algo.analyzer_supported {
+            return Err(FlasherError::AnalyzerNotSupported);
+        }
+
+        if sectors.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // The analyzer uses everything up to `analyzer_address + ANALYZER_MIN_FREE_BYTES`
+        // as its own scratch space (the code plus the 256-word CRC lookup table
+        // it builds at runtime), so results are written just past that, not
+        // right after the code.
+        let results_address = algo.analyzer_address + ANALYZER_MIN_FREE_BYTES;
+
+        // Load the analyzer into target RAM once, then run it over every sector,
+        // a batch at a time so the result buffer stays a bounded size regardless
+        // of how many sectors the image covers. The blob strides through flash
+        // as `address, address + sector_size, address + 2 * sector_size, ...`,
+        // so each call can only cover a run of contiguous, equal-sized sectors;
+        // `uniform_runs` splits `sectors` into exactly those runs (further
+        // capped at `ANALYZER_MAX_SECTORS_PER_CALL` each) instead of assuming
+        // the whole slice is uniform.
+        self.session.probe.write_block32(algo.analyzer_address, &ANALYZER);
+
+        let mut mismatched_sectors = Vec::new();
+        for batch in uniform_runs(sectors) {
+            let result = self.call_function_and_wait(
+                algo.analyzer_address,
+                Some(batch[0].base_address),
+                Some(batch.len() as u32),
+                Some(batch[0].size),
+                None,
+                false
+            )?;
+
+            if result != 0 {
+                return Err(FlasherError::Verify(result));
+            }
+
+            let mut crcs = vec![0u32; batch.len()];
+            self.session.probe.read_block32(results_address, &mut crcs);
+
+            for (sector, on_chip_crc) in batch.iter().zip(crcs) {
+                if crc32(&sector_bytes(image_base, image, sector)) != on_chip_crc {
+                    mismatched_sectors.push(sector.base_address);
+                }
+            }
+        }
+
+        Ok(mismatched_sectors)
+    }
+}
+
+/// Splits `sectors` into maximal runs the analyzer blob can process in a
+/// single call: consecutive sectors of the same `size`, each immediately
+/// following the previous one's end address, capped at
+/// `ANALYZER_MAX_SECTORS_PER_CALL` entries. A region with mixed sector sizes
+/// (or a gap) starts a new run rather than being treated as one uniform
+/// stride, which would otherwise make the blob read the wrong addresses and
+/// produce false mismatches.
+fn uniform_runs(sectors: &[SectorInfo]) -> Vec<&[SectorInfo]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+
+    while start < sectors.len() {
+        let mut end = start + 1;
+        while end < sectors.len()
+            && end - start < ANALYZER_MAX_SECTORS_PER_CALL
+            && sectors[end].size == sectors[start].size
+            && sectors[end].base_address == sectors[end - 1].base_address + sectors[end - 1].size
+        {
+            end += 1;
+        }
+
+        runs.push(&sectors[start..end]);
+        start = end;
+    }
+
+    runs
+}
+
+/// Extracts the bytes of `image` covered by `sector`, padding any bytes the image
+/// does not cover (because the sector extends past the end of the image) with
+/// `0xFF`, matching the erased state of unwritten flash.
+pub(crate) fn sector_bytes(image_base: u32, image: &[u8], sector: &SectorInfo) -> Vec<u8> {
+    let mut bytes = vec![0xFFu8; sector.size as usize];
+
+    let sector_offset = (sector.base_address - image_base) as usize;
+    if sector_offset < image.len() {
+        let available = (image.len() - sector_offset).min(bytes.len());
+        bytes[..available].copy_from_slice(&image[sector_offset..sector_offset + available]);
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc32;
+
+    #[test]
+    fn crc32_of_empty_input_is_zero() {
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        // "123456789" is the standard CRC-32/ISO-HDLC check string; this is
+        // the same reflected poly 0xEDB88320 construction the on-chip
+        // analyzer uses, so its result is a known-good reference value.
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn crc32_is_sensitive_to_single_byte_changes() {
+        assert_ne!(crc32(b"flash page a"), crc32(b"flash page b"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,353 @@
+use super::flasher::{crc32, ActiveFlasher, Erase, FlasherError, InactiveFlasher, Program};
+use super::FlashRegion;
+
+/// Sentinel left behind by erased flash: both a page header and an entry
+/// length prefix read back as all-`0xFF` until something is written over them.
+const ERASED_WORD: u32 = 0xFFFF_FFFF;
+
+const PAGE_HEADER_SIZE: u32 = 4;
+/// `key_len: u16` followed by `value_len: u16`.
+const ENTRY_HEADER_SIZE: u32 = 4;
+const ENTRY_CRC_SIZE: u32 = 4;
+
+#[derive(Debug)]
+pub enum KvError {
+    Flasher(FlasherError),
+    /// The entry does not fit in a single page of the region, even when empty.
+    EntryTooLarge,
+}
+
+impl From<FlasherError> for KvError {
+    fn from(error: FlasherError) -> Self {
+        KvError::Flasher(error)
+    }
+}
+
+/// A flash region dedicated to a key/value store, treated as a ring of
+/// fixed-size pages. Each page holds an append-only log of `(key, value)`
+/// records; the newest record for a key wins. When the active page fills up,
+/// the store rolls over to the next page in the ring and erases it, spreading
+/// writes (and therefore wear) across the whole region.
+pub struct KvRegion {
+    pub base_address: u32,
+    pub page_size: u32,
+    pub page_count: u32,
+}
+
+impl KvRegion {
+    fn page_address(&self, page: u32) -> u32 {
+        self.base_address + page * self.page_size
+    }
+}
+
+/// Result of walking a page's entry log.
+struct PageScan {
+    /// `None` if the page has never been written since it was last erased.
+    generation: Option<u32>,
+    /// Offset of the first free byte after the page header, i.e. where the
+    /// next entry should be appended.
+    free_offset: u32,
+    /// Whether every byte from `free_offset` to the end of the page reads as
+    /// erased (`0xFF`). `parse_page` stops at the first entry it can't make
+    /// sense of, which covers two very different cases: genuine free space
+    /// (nothing written there since the last erase), and a torn entry from a
+    /// write interrupted by power loss, which can leave some of its bytes
+    /// already programmed. NOR flash can't be reprogrammed without erasing
+    /// first, so appending at `free_offset` is only safe when this is `true`.
+    safe_to_append: bool,
+}
+
+/// Serializes a `(key, value)` entry as `scan_page`/`parse_page` expect:
+/// a `key_len: u16` / `value_len: u16` header, the key and value bytes, and a
+/// trailing CRC32 over the header and payload.
+fn encode_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(ENTRY_HEADER_SIZE as usize + key.len() + value.len() + ENTRY_CRC_SIZE as usize);
+    record.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    record.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    record.extend_from_slice(&crc32(&record).to_le_bytes());
+    record
+}
+
+/// Walks a page's header and entry log from an in-memory copy of its bytes,
+/// calling `on_entry` for every valid `(key, value)` entry found in append
+/// order. Stops at the first entry whose CRC marker does not match (a write
+/// that was interrupted, e.g. by power loss) or that is entirely erased (free
+/// space), since entries are only ever appended, never rewritten in place.
+/// Kept separate from `scan_page` (which reads the bytes off the target) so
+/// the parsing logic can be exercised without a flash connection.
+fn parse_page(page: &[u8], mut on_entry: impl FnMut(&[u8], &[u8])) -> PageScan {
+    let page_size = page.len() as u32;
+
+    let header = u32::from_le_bytes(page[..PAGE_HEADER_SIZE as usize].try_into().unwrap());
+    let generation = (header != ERASED_WORD).then_some(header);
+
+    let mut offset = PAGE_HEADER_SIZE;
+    while offset + ENTRY_HEADER_SIZE <= page_size {
+        let length_prefix = &page[offset as usize..(offset + ENTRY_HEADER_SIZE) as usize];
+        let key_len = u16::from_le_bytes([length_prefix[0], length_prefix[1]]) as u32;
+        let value_len = u16::from_le_bytes([length_prefix[2], length_prefix[3]]) as u32;
+
+        // An erased length prefix marks the end of this page's log.
+        if key_len == 0xFFFF && value_len == 0xFFFF {
+            break;
+        }
+
+        let entry_size = ENTRY_HEADER_SIZE + key_len + value_len + ENTRY_CRC_SIZE;
+        if offset + entry_size > page_size {
+            break;
+        }
+
+        let payload_start = (offset + ENTRY_HEADER_SIZE) as usize;
+        let payload = &page[payload_start..payload_start + (key_len + value_len) as usize];
+        let crc_start = payload_start + (key_len + value_len) as usize;
+        let crc_bytes: [u8; ENTRY_CRC_SIZE as usize] =
+            page[crc_start..crc_start + ENTRY_CRC_SIZE as usize].try_into().unwrap();
+
+        let mut crc_input = length_prefix.to_vec();
+        crc_input.extend_from_slice(payload);
+        if crc32(&crc_input) != u32::from_le_bytes(crc_bytes) {
+            break;
+        }
+
+        on_entry(&payload[..key_len as usize], &payload[key_len as usize..]);
+        offset += entry_size;
+    }
+
+    let safe_to_append = page[offset as usize..].iter().all(|&byte| byte == 0xFF);
+    PageScan { generation, free_offset: offset, safe_to_append }
+}
+
+/// Reads a page off the target and walks its entry log; see `parse_page`.
+fn scan_page(
+    program: &mut ActiveFlasher<Program>,
+    region: &KvRegion,
+    page: u32,
+    on_entry: impl FnMut(&[u8], &[u8]),
+) -> PageScan {
+    let mut bytes = vec![0u8; region.page_size as usize];
+    program.read_bytes(region.page_address(page), &mut bytes);
+    parse_page(&bytes, on_entry)
+}
+
+/// Returns the page with the highest generation number (the one most recently
+/// rolled onto), along with how full it is. Pages that were never written
+/// (still fully erased) are ignored.
+fn find_active_page(program: &mut ActiveFlasher<Program>, region: &KvRegion) -> Option<(u32, PageScan)> {
+    highest_generation((0..region.page_count).map(|page| (page, scan_page(program, region, page, |_, _| {}))))
+}
+
+/// Picks the `(page, scan)` pair with the highest generation number, ignoring
+/// pages that were never written.
+fn highest_generation(scans: impl IntoIterator<Item = (u32, PageScan)>) -> Option<(u32, PageScan)> {
+    scans
+        .into_iter()
+        .filter_map(|(page, scan)| scan.generation.map(|generation| (generation, page, scan)))
+        .max_by_key(|(generation, _, _)| *generation)
+        .map(|(_, page, scan)| (page, scan))
+}
+
+/// Appends `(key, value)` to the store, rolling over to the next page (and
+/// erasing it) if the active page has no room left.
+///
+/// Takes the `InactiveFlasher` rather than pre-built `Erase`/`Program`
+/// flashers: the flash algorithm is init'd for one operation at a time, and a
+/// single `Session` can't back two simultaneously-active flashers, so this
+/// switches operations internally (uninit/re-init) whenever a page roll-over
+/// needs an erase in between program calls. `inactive` is left holding the
+/// session again once the store completes, whether it succeeds or fails.
+pub fn store(
+    inactive: &mut InactiveFlasher,
+    flash_region: FlashRegion,
+    region: &KvRegion,
+    key: &[u8],
+    value: &[u8],
+) -> Result<(), KvError> {
+    if key.len() > u16::MAX as usize || value.len() > u16::MAX as usize {
+        return Err(KvError::EntryTooLarge);
+    }
+
+    let entry_size = ENTRY_HEADER_SIZE + key.len() as u32 + value.len() as u32 + ENTRY_CRC_SIZE;
+    if PAGE_HEADER_SIZE + entry_size > region.page_size {
+        return Err(KvError::EntryTooLarge);
+    }
+
+    let mut program = inactive.init::<Program>(flash_region, None, None)?;
+
+    let active = find_active_page(&mut program, region);
+    let (page, free_offset) = match &active {
+        Some((page, scan)) if scan.free_offset + entry_size <= region.page_size && scan.safe_to_append => {
+            (*page, scan.free_offset)
+        }
+        _ => {
+            // The active page (if any) is full, or its tail past free_offset
+            // isn't all erased (a torn entry from an interrupted write left
+            // some of it already programmed). Either way we can't safely
+            // append here, so roll over to the next page in the ring,
+            // erasing it. This evicts the oldest data and is what gives the
+            // store its wear leveling.
+            let next_page = active.as_ref().map_or(0, |(page, _)| (page + 1) % region.page_count);
+            let next_generation = active.as_ref().and_then(|(_, scan)| scan.generation).unwrap_or(0) + 1;
+
+            let mut erase = program.uninit()?.init::<Erase>(flash_region, None, None)?;
+            erase.erase_sector(region.page_address(next_page))?;
+            program = erase.uninit()?.init::<Program>(flash_region, None, None)?;
+
+            program.program_page(region.page_address(next_page), &next_generation.to_le_bytes())?;
+
+            (next_page, PAGE_HEADER_SIZE)
+        }
+    };
+
+    let record = encode_record(key, value);
+    program.program_page(region.page_address(page) + free_offset, &record)?;
+
+    *inactive = program.uninit()?;
+
+    Ok(())
+}
+
+/// Looks up the most recently stored value for `key`, or `None` if it was
+/// never written (or was written only to a page that has since been evicted).
+pub fn fetch(
+    inactive: &mut InactiveFlasher,
+    flash_region: FlashRegion,
+    region: &KvRegion,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, KvError> {
+    let mut program = inactive.init::<Program>(flash_region, None, None)?;
+
+    // Process pages oldest-to-newest by generation, so the last matching entry
+    // we see across the whole region is the most recent write.
+    let mut pages: Vec<(u32, u32)> = (0..region.page_count)
+        .filter_map(|page| {
+            let scan = scan_page(&mut program, region, page, |_, _| {});
+            scan.generation.map(|generation| (generation, page))
+        })
+        .collect();
+    pages.sort_by_key(|(generation, _)| *generation);
+
+    let mut latest = None;
+    for (_, page) in pages {
+        scan_page(&mut program, region, page, |entry_key, entry_value| {
+            if entry_key == key {
+                latest = Some(entry_value.to_vec());
+            }
+        });
+    }
+
+    *inactive = program.uninit()?;
+
+    Ok(latest)
+}
+
+/// Erases every page in `region`, discarding all stored key/value data.
+pub fn erase(inactive: &mut InactiveFlasher, flash_region: FlashRegion, region: &KvRegion) -> Result<(), KvError> {
+    let mut erase = inactive.init::<Erase>(flash_region, None, None)?;
+
+    for page in 0..region.page_count {
+        erase.erase_sector(region.page_address(page))?;
+    }
+
+    *inactive = erase.uninit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a page buffer: the generation header, followed by `records`
+    /// concatenated, followed by erased (`0xFF`) fill out to `page_size`.
+    fn page_with_records(page_size: u32, generation: u32, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = vec![0xFFu8; page_size as usize];
+        page[..PAGE_HEADER_SIZE as usize].copy_from_slice(&generation.to_le_bytes());
+
+        let mut offset = PAGE_HEADER_SIZE as usize;
+        for record in records {
+            page[offset..offset + record.len()].copy_from_slice(record);
+            offset += record.len();
+        }
+
+        page
+    }
+
+    #[test]
+    fn encode_record_round_trips_through_parse_page() {
+        let record = encode_record(b"key", b"value");
+        let page = page_with_records(64, 1, std::slice::from_ref(&record));
+
+        let mut seen = Vec::new();
+        let scan = parse_page(&page, |key, value| seen.push((key.to_vec(), value.to_vec())));
+
+        assert_eq!(scan.generation, Some(1));
+        assert_eq!(seen, vec![(b"key".to_vec(), b"value".to_vec())]);
+        assert_eq!(scan.free_offset, PAGE_HEADER_SIZE + record.len() as u32);
+        assert!(scan.safe_to_append);
+    }
+
+    #[test]
+    fn parse_page_reports_every_entry_for_a_repeated_key_in_append_order() {
+        let records = vec![encode_record(b"key", b"old"), encode_record(b"key", b"new")];
+        let page = page_with_records(64, 1, &records);
+
+        let mut seen = Vec::new();
+        parse_page(&page, |key, value| seen.push((key.to_vec(), value.to_vec())));
+
+        assert_eq!(seen, vec![(b"key".to_vec(), b"old".to_vec()), (b"key".to_vec(), b"new".to_vec())]);
+    }
+
+    #[test]
+    fn parse_page_on_a_freshly_erased_page_has_no_generation_and_no_entries() {
+        let page = vec![0xFFu8; 64];
+
+        let mut entries = 0;
+        let scan = parse_page(&page, |_, _| entries += 1);
+
+        assert_eq!(scan.generation, None);
+        assert_eq!(entries, 0);
+        assert_eq!(scan.free_offset, PAGE_HEADER_SIZE);
+        assert!(scan.safe_to_append);
+    }
+
+    #[test]
+    fn parse_page_stops_at_a_partially_written_entry_and_flags_it_unsafe_to_append_to() {
+        // Simulates power loss mid-write: the first entry is complete, the
+        // second has its length prefix written but was cut off before its
+        // CRC landed, so its CRC bytes are still erased.
+        let complete = encode_record(b"a", b"1");
+        let mut page = page_with_records(64, 1, &[complete.clone()]);
+
+        let torn = encode_record(b"bb", b"22");
+        let torn_offset = PAGE_HEADER_SIZE as usize + complete.len();
+        let torn_without_crc = &torn[..torn.len() - ENTRY_CRC_SIZE as usize];
+        page[torn_offset..torn_offset + torn_without_crc.len()].copy_from_slice(torn_without_crc);
+
+        let mut seen = Vec::new();
+        let scan = parse_page(&page, |key, value| seen.push((key.to_vec(), value.to_vec())));
+
+        assert_eq!(seen, vec![(b"a".to_vec(), b"1".to_vec())]);
+        assert_eq!(scan.free_offset, PAGE_HEADER_SIZE + complete.len() as u32);
+        // The torn entry's length prefix already landed in flash, so the
+        // bytes from free_offset onward aren't erased: appending here would
+        // try to reprogram them, which NOR flash can't do safely.
+        assert!(!scan.safe_to_append);
+    }
+
+    #[test]
+    fn find_active_page_among_scans_picks_highest_generation() {
+        let scans = [
+            PageScan { generation: Some(3), free_offset: 10, safe_to_append: true },
+            PageScan { generation: None, free_offset: 4, safe_to_append: true },
+            PageScan { generation: Some(7), free_offset: 20, safe_to_append: true },
+        ];
+
+        let best = highest_generation(scans.into_iter().enumerate().map(|(page, scan)| (page as u32, scan)));
+
+        let (page, scan) = best.expect("at least one written page");
+        assert_eq!(page, 2);
+        assert_eq!(scan.free_offset, 20);
+    }
+}
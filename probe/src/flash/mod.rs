@@ -0,0 +1,13 @@
+//! Flash algorithm primitives (`flasher`), the download pipeline that ties
+//! them together (`download`), and a log-structured key/value store built on
+//! top of them (`kv_store`).
+//!
+//! `FlashRegion`, `SectorInfo`, `PageInfo`, and `FlashInfo` (the region/sector
+//! descriptor types `flasher` and `download` build on) predate this module
+//! file and live alongside it as before; nothing here changes them.
+
+pub mod flasher;
+pub mod download;
+pub mod kv_store;
+
+pub use kv_store::{erase, fetch, store, KvError, KvRegion};